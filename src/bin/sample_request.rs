@@ -1,90 +1,176 @@
-use arrow::array::StringArray;
-use arrow::datatypes::{DataType, Field, Schema};
-use arrow::record_batch::RecordBatch;
-use clap::Parser;
-use itertools::Itertools;
-use parquet::arrow::arrow_writer::ArrowWriter;
-use parquet::file::properties::WriterProperties;
-use resas_downloader::{client, schema};
-use std::fs::File;
-use std::sync::Arc;
-use std::{str, thread, time::Duration};
-
-const RESAS_PATH_PREFECTURE: &str = "api/v1/prefectures";
-const RESAS_PATH_CITY: &str = "api/v1/cities";
-const INTERVAL_MILLIS: u64 = 200;
+use clap::{Parser, Subcommand};
+use resas_downloader::{client, downloader, error::Error, sink::Format};
+use std::process::ExitCode;
+use std::time::Duration;
 
 #[derive(Parser)]
-struct Args {
+#[command(name = "resas-downloader")]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Args)]
+struct GlobalArgs {
+    /// RESAS-API token
+    #[arg(long, env = "RESAS_TOKEN")]
     token: String,
+    /// Minimum delay between requests leaving the client, in milliseconds
+    #[arg(long, default_value_t = 200)]
+    interval_millis: u64,
+    /// Number of requests allowed in flight at once
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(usize).range(1..))]
+    concurrency: usize,
+    /// Number of attempts before giving up on a request
+    #[arg(long, default_value_t = 3)]
+    retry_attempts: u64,
+    /// Seconds to wait before the first retry; doubles on each subsequent attempt
+    #[arg(long, default_value_t = 1)]
+    retry_base_interval: u64,
+    /// Cap on the backoff delay between retries, in seconds
+    #[arg(long, default_value_t = 60)]
+    retry_max_interval: u64,
+    /// Randomize the backoff delay instead of using the computed value exactly
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    retry_jitter: bool,
+}
+
+impl GlobalArgs {
+    fn retry_policy(&self) -> client::RetryPolicy {
+        client::RetryPolicy::new(
+            self.retry_base_interval,
+            self.retry_max_interval,
+            self.retry_attempts,
+            self.retry_jitter,
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch every prefecture
+    Prefectures {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Fetch every city, joined with its prefecture name
+    Cities {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Fetch an arbitrary RESAS endpoint and print its JSON result
+    Fetch {
+        /// RESAS API path, e.g. api/v1/prefectures
+        #[arg(long)]
+        path: String,
+        /// Query parameter as key=value; may be repeated
+        #[arg(long = "param")]
+        params: Vec<String>,
+    },
+}
+
+#[derive(clap::Args)]
+struct OutputArgs {
+    /// Path to write output to
+    #[arg(long)]
     output_path: String,
+    /// Output format; inferred from output-path's extension if omitted
+    #[arg(long)]
+    format: Option<Format>,
 }
-pub fn main() {
-    let args = Args::parse();
-    let (token, output_path) = (&args.token, &args.output_path);
-    let client = client::Client::new(String::from(token.as_str()), client::RetryPolicy::default());
-    let prefectures = match client.get::<schema::Prefecture>(RESAS_PATH_PREFECTURE, None, true) {
-        Ok(p) => p.result,
-        Err(e) => panic!("Failed to get request: {}", e),
+
+#[tokio::main]
+pub async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let async_client = client::AsyncClient::new(cli.global.token.clone(), cli.global.retry_policy());
+
+    let result = match &cli.command {
+        Command::Prefectures { output } => {
+            downloader::download_prefectures(&async_client, &output.output_path, output.format)
+                .await
+                .map(|()| println!("Saved to {}", output.output_path))
+        }
+        Command::Cities { output } => downloader::download_cities(
+            &async_client,
+            Duration::from_millis(cli.global.interval_millis),
+            cli.global.concurrency,
+            &output.output_path,
+            output.format,
+        )
+        .await
+        .map(|()| println!("Saved to {}", output.output_path)),
+        Command::Fetch { path, params } => fetch_generic(&async_client, path, params).await,
     };
 
-    let rows_iter = prefectures
-        .iter()
-        .flat_map(|p| {
-            thread::sleep(Duration::from_millis(INTERVAL_MILLIS));
-            let cities = client
-                .get::<schema::City>(
-                    RESAS_PATH_CITY,
-                    Some(&format!("prefCode={}", p.pref_code)),
-                    true,
-                )
-                .expect("Failed to get request")
-                .result;
-            println!("Fetched prefecture: {}", p.pref_name);
-            cities.into_iter().map(|c| {
-                vec![
-                    c.pref_code.to_string(),
-                    p.pref_name.clone(),
-                    c.city_code,
-                    c.city_name,
-                    c.big_city_flag,
-                ]
-            })
-        })
-        .collect_vec();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn fetch_generic(
+    async_client: &client::AsyncClient,
+    path: &str,
+    params: &[String],
+) -> Result<(), Error> {
+    let parameters = (!params.is_empty()).then(|| params.join("&"));
+    let response = async_client
+        .get::<serde_json::Value>(path, parameters.as_deref(), true)
+        .await?;
+    if let Some(message) = response.message() {
+        println!("RESAS message: {}", message);
+    }
+    let result = response.into_result()?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
 
-    let n_columns = rows_iter
-        .get(0)
-        .expect("Not found data for city results")
-        .len();
+    fn parse(args: &[&str]) -> Cli {
+        let mut full = vec!["resas-downloader"];
+        full.extend_from_slice(args);
+        Cli::try_parse_from(full).unwrap()
+    }
 
-    //Transpose rows to columner.
-    let columns_iter = (0..n_columns).map(|i| rows_iter.iter().map(move |j| j[i].clone()));
+    #[test]
+    fn retry_jitter_can_be_explicitly_disabled() {
+        let cli = parse(&["--token", "t", "--retry-jitter", "false", "fetch", "--path", "p"]);
+        assert!(!cli.global.retry_jitter);
+    }
 
-    let columns = columns_iter
-        .map(|column| Arc::new(StringArray::from(column.collect_vec())) as arrow::array::ArrayRef)
-        .collect_vec();
+    #[test]
+    fn retry_jitter_defaults_to_true() {
+        let cli = parse(&["--token", "t", "fetch", "--path", "p"]);
+        assert!(cli.global.retry_jitter);
+    }
 
-    let batch_cities = RecordBatch::try_new(
-        Arc::new(Schema::new(vec![
-            Field::new("prefecture_code", DataType::Utf8, false),
-            Field::new("prefecture_name", DataType::Utf8, false),
-            Field::new("city_code", DataType::Utf8, false),
-            Field::new("city_name", DataType::Utf8, false),
-            Field::new("big_city_flag_array", DataType::Utf8, false),
-        ])),
-        columns,
-    )
-    .expect("Failed to genearte RecordBatch");
+    #[test]
+    fn concurrency_zero_is_rejected() {
+        let result = Cli::try_parse_from([
+            "resas-downloader",
+            "--token",
+            "t",
+            "--concurrency",
+            "0",
+            "fetch",
+            "--path",
+            "p",
+        ]);
+        assert!(result.is_err());
+    }
 
-    let file =
-        File::create(output_path).expect(&format!("Failed to create file at {}", output_path));
-    let props = WriterProperties::builder().build();
-    let mut writer = ArrowWriter::try_new(file, batch_cities.schema(), Some(props))
-        .expect("Failed to create writer!");
-    writer
-        .write(&batch_cities)
-        .expect("Failed to write RecordBatch");
-    writer.close().expect("Failed to close writer");
-    println!("Saved to {}", output_path);
+    #[test]
+    fn fetch_does_not_require_an_output_path() {
+        let cli = parse(&["--token", "t", "fetch", "--path", "p"]);
+        assert!(matches!(cli.command, Command::Fetch { .. }));
+    }
 }
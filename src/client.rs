@@ -1,37 +1,152 @@
-use crate::error::{Error, ErrorKind};
+use crate::error::Error;
 use std::{thread, time};
 
+use futures::stream::{self, StreamExt};
+use rand::Rng;
 use reqwest;
 use serde::{de::DeserializeOwned, Deserialize};
-use serde_json::Value;
+use std::sync::Arc;
 use std::vec;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 const RESAS_ENDPOINT: &str = "https://opendata.resas-portal.go.jp";
 
+/// Parses a `Retry-After` header value, which is either a number of seconds or an HTTP-date.
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<time::Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(time::Duration::from_secs(seconds));
+    }
+    let target = httpdate::parse_http_date(value).ok()?;
+    target.duration_since(time::SystemTime::now()).ok()
+}
+
+/// A minimal probe for RESAS's embedded-error quirk (a `statusCode`/`message` body despite a
+/// 200 HTTP status), used by `send_request` to detect it without deserializing into the
+/// caller's target type.
+#[derive(Debug, Deserialize)]
+struct StatusProbe {
+    #[serde(rename = "statusCode")]
+    status_code: Option<u16>,
+    message: Option<String>,
+}
+
+/// RESAS-API returns an error `statusCode`/`message` in its body although the status in its
+/// response header is 200. This must be checked here, inside `send_request`, rather than only
+/// after retries have already been exhausted, so `RetryPolicy` actually sees and retries it.
+fn check_embedded_error(response_text: &str) -> Result<(), Error> {
+    let probe: StatusProbe = serde_json::from_str(response_text)?;
+    if let Some(status_code) = probe.status_code.filter(|code| !(200..300).contains(code)) {
+        return Err(Error::ResasBody {
+            status_code,
+            message: probe.message.unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+/// RESAS's response envelope. `result` is generic rather than forced into a `Vec`, since many
+/// endpoints return an object (or a nested, paginated shape) rather than an array.
 #[derive(Debug, Deserialize)]
 pub struct ResasResponse<T> {
+    #[serde(rename = "statusCode")]
+    status_code: Option<u16>,
     message: Option<String>,
-    #[serde(bound(deserialize = "Vec<T>: Deserialize<'de>"))]
-    pub result: Vec<T>,
+    result: Option<T>,
+}
+
+impl<T> ResasResponse<T> {
+    /// The top-level `message` RESAS returns alongside a result.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Unwraps the response, turning RESAS's quirk of embedding an error `statusCode`/
+    /// `message` in an otherwise-200 body (and omitting `result` entirely) into an `Error`.
+    pub fn into_result(self) -> Result<T, Error> {
+        if let Some(status_code) = self.status_code.filter(|code| !(200..300).contains(code)) {
+            return Err(Error::ResasBody {
+                status_code,
+                message: self.message.unwrap_or_default(),
+            });
+        }
+        self.result.ok_or_else(|| Error::ResasBody {
+            status_code: self.status_code.unwrap_or(200),
+            message: self
+                .message
+                .unwrap_or_else(|| "RESAS response had no result".to_string()),
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct RetryPolicy {
-    retriable_codes: Vec<String>,
-    interval: u64,
+    retriable_codes: Vec<u16>,
+    base_interval: u64,
+    max_interval: u64,
     attempts: u64,
+    jitter: bool,
 }
 
 impl Default for RetryPolicy {
     fn default() -> Self {
         Self {
-            retriable_codes: vec![String::from("500"), String::from("502")],
-            interval: 60,
+            retriable_codes: vec![500, 502],
+            base_interval: 1,
+            max_interval: 60,
             attempts: 3,
+            jitter: true,
         }
     }
 }
 
+impl RetryPolicy {
+    /// Exponential backoff: attempt *n* sleeps `min(max_interval, base_interval * 2^(n-1))`
+    /// seconds, with optional full jitter.
+    pub fn new(base_interval: u64, max_interval: u64, attempts: u64, jitter: bool) -> Self {
+        Self {
+            base_interval,
+            max_interval,
+            attempts,
+            jitter,
+            ..Self::default()
+        }
+    }
+
+    /// A fixed `interval`-second delay between every attempt, matching the original
+    /// (pre-backoff) `RetryPolicy` behavior, except that a server `Retry-After` hint still
+    /// takes precedence over the fixed delay.
+    pub fn fixed(interval: u64, attempts: u64) -> Self {
+        Self {
+            base_interval: interval,
+            max_interval: interval,
+            attempts,
+            jitter: false,
+            ..Self::default()
+        }
+    }
+
+    /// The delay to sleep before the next attempt. Honors the server's `Retry-After` hint
+    /// when present, falling back to the computed exponential backoff otherwise.
+    fn backoff_for(&self, attempt: u64, retry_after: Option<time::Duration>) -> time::Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after;
+        }
+        let exponent = (attempt.saturating_sub(1)) as u32;
+        let computed = self
+            .base_interval
+            .saturating_mul(2u64.saturating_pow(exponent))
+            .min(self.max_interval);
+        let secs = if self.jitter {
+            rand::thread_rng().gen_range(0..=computed)
+        } else {
+            computed
+        };
+        time::Duration::from_secs(secs)
+    }
+}
+
 pub struct Client {
     client: reqwest::blocking::Client,
     api_key: String,
@@ -49,10 +164,10 @@ impl Client {
     fn send_request_with_retry(&self, url: &str) -> Result<String, Error> {
         let mut attempts = 0;
         loop {
-            let mut err = match self.send_request(url) {
+            let err = match self.send_request(url) {
                 Ok(r) => return Ok(r),
                 Err(err) => {
-                    if !err.is_retriable() {
+                    if !err.is_retriable(&self.retry_policy.retriable_codes) {
                         return Err(err);
                     }
                     err
@@ -60,72 +175,140 @@ impl Client {
             };
             attempts += 1;
             if attempts == self.retry_policy.attempts {
-                return Err(
-                    err.to_fatal(Some(format!("Retried {} but couldn't recover", attempts)))
-                );
+                return Err(Error::RetriesExhausted {
+                    attempts,
+                    source: Box::new(err),
+                });
             }
-            thread::sleep(time::Duration::from_secs(self.retry_policy.interval));
+            let retry_after = err.retry_after();
+            thread::sleep(self.retry_policy.backoff_for(attempts, retry_after));
         }
     }
     fn send_request(&self, url: &str) -> Result<String, Error> {
-        let result = self
+        let response = self
             .client
             .get(url)
             .header("X-API-KEY", &self.api_key)
-            .send();
-
-        match result?.error_for_status() {
-            Ok(response) => {
-                let resopnse_text = response.text()?;
-                let response_json: Value = serde_json::from_str(resopnse_text.as_str())?;
-
-                // RESAS-API returns error status code in its body although the status in its response header is 200.
-                if let Some(status_code) = response_json.get("statusCode") {
-                    if self
-                        .retry_policy
-                        .retriable_codes
-                        .contains(&status_code.to_string())
-                    {
-                        return Err(Error::new(
-                            ErrorKind::Retryable,
-                            None,
-                            Some(response_json["message"].to_string()),
-                        ));
-                    }
-                    if status_code.to_string().starts_with("2") {
-                        return Ok(resopnse_text);
-                    }
-                    return Err(Error::new(
-                        ErrorKind::Fatal,
-                        None,
-                        Some(format!(
-                            "{status_code} {message}",
-                            status_code = status_code,
-                            message = response_json["message"],
-                        )),
-                    ));
-                }
-                Ok(resopnse_text)
+            .send()?;
+        let retry_after = parse_retry_after(response.headers());
+        let response = response.error_for_status().map_err(|err| Error::Http {
+            status: err.status().map(|s| s.as_u16()).unwrap_or_default(),
+            retry_after,
+            source: err,
+        })?;
+
+        let response_text = response.text()?;
+        check_embedded_error(&response_text)?;
+        Ok(response_text)
+    }
+    pub fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        parameters: Option<&str>,
+        with_retry: bool,
+    ) -> Result<ResasResponse<T>, Error> {
+        let url = format!("{}/{}", RESAS_ENDPOINT, path);
+        let url = match parameters {
+            None => url,
+            Some(p) => format!("{}?{}", url, p),
+        };
+        let response_text = match with_retry {
+            true => self.send_request_with_retry(&url),
+            false => self.send_request(&url),
+        }?;
+        Ok(serde_json::from_str(response_text.as_str())?)
+    }
+}
+
+/// Caps the rate at which requests are allowed to go out, independent of how many
+/// are in flight at once. Shared between concurrent tasks via an `Arc`.
+pub struct RateLimiter {
+    min_interval: time::Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: time::Duration) -> Self {
+        RateLimiter {
+            min_interval,
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Blocks until at least `min_interval` has passed since the previous call returned.
+    async fn wait_turn(&self) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            let elapsed = last.elapsed();
+            if elapsed < self.min_interval {
+                tokio::time::sleep(self.min_interval - elapsed).await;
             }
-            Err(err) => {
-                if let Some(status_code) = err.status() {
-                    if self
-                        .retry_policy
-                        .retriable_codes
-                        .contains(&status_code.to_string())
-                    {
-                        return Err(Error::new(
-                            ErrorKind::Retryable,
-                            Some(Box::from(err)),
-                            Some(format!("Status code {}", status_code)),
-                        ));
+        }
+        *last_request = Some(Instant::now());
+    }
+}
+
+/// Async, connection-pooled counterpart to [`Client`], for bulk extraction jobs that want
+/// to fan requests out instead of serializing them one at a time.
+pub struct AsyncClient {
+    client: reqwest::Client,
+    api_key: String,
+    retry_policy: RetryPolicy,
+}
+
+impl AsyncClient {
+    pub fn new(api_key: String, retry_policy: RetryPolicy) -> Self {
+        AsyncClient {
+            client: reqwest::Client::new(),
+            api_key,
+            retry_policy,
+        }
+    }
+
+    async fn send_request_with_retry(&self, url: &str) -> Result<String, Error> {
+        let mut attempts = 0;
+        loop {
+            let err = match self.send_request(url).await {
+                Ok(r) => return Ok(r),
+                Err(err) => {
+                    if !err.is_retriable(&self.retry_policy.retriable_codes) {
+                        return Err(err);
                     }
+                    err
                 }
-                Err(Error::from(err))
+            };
+            attempts += 1;
+            if attempts == self.retry_policy.attempts {
+                return Err(Error::RetriesExhausted {
+                    attempts,
+                    source: Box::new(err),
+                });
             }
+            let retry_after = err.retry_after();
+            tokio::time::sleep(self.retry_policy.backoff_for(attempts, retry_after)).await;
         }
     }
-    pub fn get<T: DeserializeOwned>(
+
+    async fn send_request(&self, url: &str) -> Result<String, Error> {
+        let response = self
+            .client
+            .get(url)
+            .header("X-API-KEY", &self.api_key)
+            .send()
+            .await?;
+        let retry_after = parse_retry_after(response.headers());
+        let response = response.error_for_status().map_err(|err| Error::Http {
+            status: err.status().map(|s| s.as_u16()).unwrap_or_default(),
+            retry_after,
+            source: err,
+        })?;
+
+        let response_text = response.text().await?;
+        check_embedded_error(&response_text)?;
+        Ok(response_text)
+    }
+
+    pub async fn get<T: DeserializeOwned>(
         &self,
         path: &str,
         parameters: Option<&str>,
@@ -137,9 +320,125 @@ impl Client {
             Some(p) => format!("{}?{}", url, p),
         };
         let response_text = match with_retry {
-            true => self.send_request_with_retry(&url),
-            false => self.send_request(&url),
+            true => self.send_request_with_retry(&url).await,
+            false => self.send_request(&url).await,
         }?;
         Ok(serde_json::from_str(response_text.as_str())?)
     }
+
+    /// Fans `path`/`parameters` pairs out with at most `concurrency` requests in flight,
+    /// honoring `rate_limiter` as a floor on the gap between requests leaving the client.
+    /// Results may arrive in a different order than `requests` since faster responses
+    /// complete first. `concurrency` is clamped to at least 1: `buffer_unordered(0)` never
+    /// polls its inner futures, which would otherwise hang forever.
+    pub async fn get_many<T: DeserializeOwned + Send + 'static>(
+        &self,
+        requests: Vec<(String, Option<String>)>,
+        concurrency: usize,
+        with_retry: bool,
+        rate_limiter: Arc<RateLimiter>,
+    ) -> Vec<Result<ResasResponse<T>, Error>> {
+        stream::iter(requests.into_iter().map(|(path, parameters)| {
+            let rate_limiter = Arc::clone(&rate_limiter);
+            async move {
+                rate_limiter.wait_turn().await;
+                self.get(&path, parameters.as_deref(), with_retry).await
+            }
+        }))
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_for_without_jitter_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::new(1, 10, 5, false);
+        assert_eq!(policy.backoff_for(1, None), time::Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(2, None), time::Duration::from_secs(2));
+        assert_eq!(policy.backoff_for(3, None), time::Duration::from_secs(4));
+        assert_eq!(policy.backoff_for(4, None), time::Duration::from_secs(8));
+        // Would be 16 uncapped; clamped to max_interval.
+        assert_eq!(policy.backoff_for(5, None), time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn backoff_for_with_jitter_never_exceeds_the_computed_delay() {
+        let policy = RetryPolicy::new(1, 10, 5, true);
+        for attempt in 1..=5 {
+            let delay = policy.backoff_for(attempt, None);
+            let computed = (1u64 << (attempt - 1)).min(10);
+            assert!(delay <= time::Duration::from_secs(computed));
+        }
+    }
+
+    #[test]
+    fn backoff_for_prefers_retry_after_over_computed_backoff() {
+        let policy = RetryPolicy::new(1, 60, 5, false);
+        let retry_after = time::Duration::from_secs(30);
+        assert_eq!(policy.backoff_for(1, Some(retry_after)), retry_after);
+    }
+
+    #[test]
+    fn parse_retry_after_reads_seconds() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(
+            parse_retry_after(&headers),
+            Some(time::Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_returns_none_when_absent() {
+        let headers = reqwest::header::HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn into_result_returns_the_result_on_success() {
+        let response = ResasResponse {
+            status_code: Some(200),
+            message: None,
+            result: Some(vec![1, 2, 3]),
+        };
+        assert_eq!(response.into_result().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn into_result_surfaces_an_embedded_error_status_code() {
+        let response: ResasResponse<Vec<i32>> = ResasResponse {
+            status_code: Some(500),
+            message: Some("internal error".to_string()),
+            result: None,
+        };
+        let err = response.into_result().unwrap_err();
+        assert_eq!(err.status_code(), Some(500));
+    }
+
+    #[test]
+    fn into_result_errors_when_result_is_missing_despite_a_200_status() {
+        let response: ResasResponse<Vec<i32>> = ResasResponse {
+            status_code: Some(200),
+            message: None,
+            result: None,
+        };
+        assert!(response.into_result().is_err());
+    }
+
+    #[test]
+    fn check_embedded_error_ignores_a_clean_200_body() {
+        assert!(check_embedded_error(r#"{"statusCode":200,"result":[]}"#).is_ok());
+    }
+
+    #[test]
+    fn check_embedded_error_catches_a_500_embedded_in_a_200_response() {
+        let err =
+            check_embedded_error(r#"{"statusCode":500,"message":"oops"}"#).unwrap_err();
+        assert_eq!(err.status_code(), Some(500));
+    }
 }
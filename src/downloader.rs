@@ -0,0 +1,139 @@
+use crate::client;
+use crate::error::Error;
+use crate::schema;
+use crate::sink::{AnySink, Format, RecordSink};
+use arrow::array::{ArrayRef, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+const RESAS_PATH_PREFECTURE: &str = "api/v1/prefectures";
+const RESAS_PATH_CITY: &str = "api/v1/cities";
+
+/// Fetches every prefecture and writes it to `output_path`, in `format` (or a format inferred
+/// from `output_path`'s extension if `None`).
+pub async fn download_prefectures(
+    async_client: &client::AsyncClient,
+    output_path: &str,
+    format: Option<Format>,
+) -> Result<(), Error> {
+    let response = async_client
+        .get::<Vec<schema::Prefecture>>(RESAS_PATH_PREFECTURE, None, true)
+        .await?;
+    if let Some(message) = response.message() {
+        println!("RESAS message: {}", message);
+    }
+    let prefectures = response.into_result()?;
+
+    let rows = prefectures
+        .iter()
+        .map(|p| vec![p.pref_code.to_string(), p.pref_name.clone()])
+        .collect_vec();
+
+    write_output(
+        &rows,
+        vec![
+            Field::new("prefecture_code", DataType::Utf8, false),
+            Field::new("prefecture_name", DataType::Utf8, false),
+        ],
+        output_path,
+        format,
+    )
+}
+
+/// Fetches every city, joined with its prefecture name, and writes it to `output_path`, in
+/// `format` (or a format inferred from `output_path`'s extension if `None`). Per-prefecture
+/// city requests are fanned out with at most `concurrency` in flight, no closer together than
+/// `interval`.
+pub async fn download_cities(
+    async_client: &client::AsyncClient,
+    interval: Duration,
+    concurrency: usize,
+    output_path: &str,
+    format: Option<Format>,
+) -> Result<(), Error> {
+    let response = async_client
+        .get::<Vec<schema::Prefecture>>(RESAS_PATH_PREFECTURE, None, true)
+        .await?;
+    if let Some(message) = response.message() {
+        println!("RESAS message: {}", message);
+    }
+    let prefectures = response.into_result()?;
+
+    let pref_names: HashMap<u8, String> = prefectures
+        .iter()
+        .map(|p| (p.pref_code, p.pref_name.clone()))
+        .collect();
+
+    let requests = prefectures
+        .iter()
+        .map(|p| {
+            (
+                RESAS_PATH_CITY.to_string(),
+                Some(format!("prefCode={}", p.pref_code)),
+            )
+        })
+        .collect_vec();
+
+    let rate_limiter = Arc::new(client::RateLimiter::new(interval));
+    let results = async_client
+        .get_many::<Vec<schema::City>>(requests, concurrency, true, rate_limiter)
+        .await;
+
+    let mut rows = Vec::new();
+    for result in results {
+        for c in result?.into_result()? {
+            let pref_name = pref_names.get(&c.pref_code).cloned().unwrap_or_default();
+            rows.push(vec![
+                c.pref_code.to_string(),
+                pref_name,
+                c.city_code,
+                c.city_name,
+                c.big_city_flag,
+            ]);
+        }
+    }
+
+    write_output(
+        &rows,
+        vec![
+            Field::new("prefecture_code", DataType::Utf8, false),
+            Field::new("prefecture_name", DataType::Utf8, false),
+            Field::new("city_code", DataType::Utf8, false),
+            Field::new("city_name", DataType::Utf8, false),
+            Field::new("big_city_flag_array", DataType::Utf8, false),
+        ],
+        output_path,
+        format,
+    )
+}
+
+fn write_output(
+    rows: &[Vec<String>],
+    fields: Vec<Field>,
+    output_path: &str,
+    format: Option<Format>,
+) -> Result<(), Error> {
+    let n_columns = fields.len();
+
+    //Transpose rows to columner. Works for zero rows too: each column is simply empty.
+    let columns: Vec<ArrayRef> = (0..n_columns)
+        .map(|i| {
+            Arc::new(StringArray::from(
+                rows.iter().map(|row| row[i].clone()).collect_vec(),
+            )) as ArrayRef
+        })
+        .collect();
+
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+
+    let format = format
+        .or_else(|| Format::from_extension(output_path))
+        .unwrap_or(Format::Parquet);
+    let mut sink = AnySink::create(format, output_path, &batch)?;
+    sink.write_batch(&batch)?;
+    sink.finish()
+}
@@ -1,83 +1,130 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 
 #[derive(Debug)]
-pub enum ErrorKind {
-    Fatal,
-    Retryable,
-}
-
-#[derive(Debug)]
-pub struct Error {
-    kind: ErrorKind,
-    source: Option<Box<dyn std::error::Error>>,
-    message: Option<String>,
+pub enum Error {
+    /// Non-2xx HTTP status, as reported by the transport layer.
+    Http {
+        status: u16,
+        /// The server's `Retry-After` hint, if it sent one.
+        retry_after: Option<Duration>,
+        source: reqwest::Error,
+    },
+    /// RESAS-API returns an error `statusCode`/`message` in its body although the status in
+    /// its response header is 200.
+    ResasBody { status_code: u16, message: String },
+    /// The response body could not be parsed as JSON, or didn't match the expected schema.
+    Decode(serde_json::Error),
+    /// The request never reached a server, or failed for a reason other than a status code.
+    Transport(reqwest::Error),
+    /// All configured retry attempts were exhausted.
+    RetriesExhausted { attempts: u64, source: Box<Error> },
+    /// Reading or writing an output file failed.
+    Io(std::io::Error),
+    /// Building a record batch from the downloaded rows, or writing it as CSV or NDJSON, failed.
+    Arrow(arrow::error::ArrowError),
+    /// Writing a record batch to a Parquet file failed.
+    Parquet(parquet::errors::ParquetError),
 }
 
 impl Error {
-    pub fn new(
-        kind: ErrorKind,
-        source: Option<Box<dyn std::error::Error>>,
-        message: Option<String>,
-    ) -> Error {
-        Error {
-            kind: kind,
-            source: source,
-            message: message,
+    /// The RESAS/HTTP status code this error carries, if any.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            Error::Http { status, .. } => Some(*status),
+            Error::ResasBody { status_code, .. } => Some(*status_code),
+            Error::Decode(_) | Error::Transport(_) => None,
+            Error::RetriesExhausted { source, .. } => source.status_code(),
+            Error::Io(_) | Error::Arrow(_) | Error::Parquet(_) => None,
         }
     }
-    pub fn is_retriable(&self) -> bool {
-        match self.kind {
-            ErrorKind::Retryable => true,
-            ErrorKind::Fatal => false,
+
+    /// Whether this error is worth retrying, given the caller's set of retriable status codes.
+    pub fn is_retriable(&self, retriable_codes: &[u16]) -> bool {
+        match self {
+            Error::RetriesExhausted { .. } => false,
+            _ => self
+                .status_code()
+                .is_some_and(|code| retriable_codes.contains(&code)),
         }
     }
-    pub fn to_fatal(&mut self, message: Option<String>) -> Self {
-        Self {
-            kind: ErrorKind::Fatal,
-            source: self.source.take(),
-            message: message,
+
+    /// The server's `Retry-After` hint, if this error carries one.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Http { retry_after, .. } => *retry_after,
+            _ => None,
         }
     }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        self.source.as_ref().map(|e| e.as_ref())
+        match self {
+            Error::Http { source, .. } => Some(source),
+            Error::ResasBody { .. } => None,
+            Error::Decode(err) => Some(err),
+            Error::Transport(err) => Some(err),
+            Error::RetriesExhausted { source, .. } => Some(source.as_ref()),
+            Error::Io(err) => Some(err),
+            Error::Arrow(err) => Some(err),
+            Error::Parquet(err) => Some(err),
+        }
     }
 }
 
 impl std::convert::From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Error {
-        Error {
-            kind: ErrorKind::Fatal,
-            source: Some(Box::from(err)),
-            message: None,
+        match err.status() {
+            Some(status) => Error::Http {
+                status: status.as_u16(),
+                retry_after: None,
+                source: err,
+            },
+            None => Error::Transport(err),
         }
     }
 }
 
 impl std::convert::From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Error {
-        Error {
-            kind: ErrorKind::Fatal,
-            source: Some(Box::from(err)),
-            message: None,
-        }
+        Error::Decode(err)
+    }
+}
+
+impl std::convert::From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl std::convert::From<arrow::error::ArrowError> for Error {
+    fn from(err: arrow::error::ArrowError) -> Error {
+        Error::Arrow(err)
+    }
+}
+
+impl std::convert::From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Error {
+        Error::Parquet(err)
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match self.kind {
-            ErrorKind::Fatal => write!(f, "Fatal error! ")?,
-            ErrorKind::Retryable => write!(f, "Retryable error! ")?,
-        }
-        if let Some(message) = self.message.as_ref() {
-            write!(f, "{}", message)?;
-        }
-        if let Some(source) = self.source.as_ref() {
-            return source.fmt(f);
+        match self {
+            Error::Http { status, .. } => write!(f, "HTTP error, status {}", status),
+            Error::ResasBody {
+                status_code,
+                message,
+            } => write!(f, "RESAS-API error {}: {}", status_code, message),
+            Error::Decode(err) => write!(f, "failed to decode response: {}", err),
+            Error::Transport(err) => write!(f, "transport error: {}", err),
+            Error::RetriesExhausted { attempts, source } => {
+                write!(f, "gave up after {} attempts: {}", attempts, source)
+            }
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Arrow(err) => write!(f, "Arrow error: {}", err),
+            Error::Parquet(err) => write!(f, "failed to write Parquet file: {}", err),
         }
-        Ok(())
     }
 }
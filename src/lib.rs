@@ -0,0 +1,5 @@
+pub mod client;
+pub mod downloader;
+pub mod error;
+pub mod schema;
+pub mod sink;
@@ -15,3 +15,25 @@ pub struct City {
     pub city_name: String,
     pub big_city_flag: String,
 }
+
+/// The nested `result` of `api/v1/population/composition/perYear`: a handful of named series
+/// (total population, working-age population, ...), each a year-by-year time series.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PopulationComposition {
+    pub boundary_year: u32,
+    pub data: Vec<PopulationSeries>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PopulationSeries {
+    pub label: String,
+    pub data: Vec<PopulationDataPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PopulationDataPoint {
+    pub year: u32,
+    pub value: u64,
+}
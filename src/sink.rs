@@ -0,0 +1,139 @@
+use crate::error::Error;
+use arrow::csv;
+use arrow::json::LineDelimitedWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_writer::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+
+/// Output format for downloaded RESAS data, selected by `--format` or inferred from the
+/// output path's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Parquet,
+    Csv,
+    NdJson,
+}
+
+impl Format {
+    /// Guesses the format from a file extension (`.parquet`, `.csv`, `.json`/`.ndjson`).
+    pub fn from_extension(output_path: &str) -> Option<Format> {
+        let extension = output_path.rsplit('.').next()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "parquet" => Some(Format::Parquet),
+            "csv" => Some(Format::Csv),
+            "json" | "ndjson" => Some(Format::NdJson),
+            _ => None,
+        }
+    }
+}
+
+/// A destination that `RecordBatch`es can be streamed into, one batch at a time.
+pub trait RecordSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error>;
+    fn finish(self) -> Result<(), Error>;
+}
+
+pub struct ParquetSink {
+    writer: ArrowWriter<File>,
+}
+
+impl ParquetSink {
+    fn new(file: File, batch: &RecordBatch) -> Result<Self, Error> {
+        let props = WriterProperties::builder().build();
+        let writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+        Ok(ParquetSink { writer })
+    }
+}
+
+impl RecordSink for ParquetSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        Ok(self.writer.write(batch)?)
+    }
+    fn finish(self) -> Result<(), Error> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+pub struct CsvSink {
+    writer: csv::Writer<File>,
+}
+
+impl CsvSink {
+    fn new(file: File) -> Self {
+        CsvSink {
+            writer: csv::WriterBuilder::new().with_header(true).build(file),
+        }
+    }
+}
+
+impl RecordSink for CsvSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        Ok(self.writer.write(batch)?)
+    }
+    fn finish(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub struct NdJsonSink {
+    writer: LineDelimitedWriter<File>,
+}
+
+impl NdJsonSink {
+    fn new(file: File) -> Self {
+        NdJsonSink {
+            writer: LineDelimitedWriter::new(file),
+        }
+    }
+}
+
+impl RecordSink for NdJsonSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        Ok(self.writer.write(batch)?)
+    }
+    fn finish(mut self) -> Result<(), Error> {
+        Ok(self.writer.finish()?)
+    }
+}
+
+/// The sink selected for a given output, dispatching to the concrete implementation.
+///
+/// `Csv` is boxed since `CsvSink` is significantly larger than the other variants
+/// (`clippy::large_enum_variant`) and this enum is moved around by value via `finish`.
+pub enum AnySink {
+    Parquet(ParquetSink),
+    Csv(Box<CsvSink>),
+    NdJson(NdJsonSink),
+}
+
+impl AnySink {
+    /// Opens `output_path` and builds a sink for `format`. Parquet needs a sample batch up
+    /// front to fix its schema; the other formats don't.
+    pub fn create(format: Format, output_path: &str, first_batch: &RecordBatch) -> Result<Self, Error> {
+        let file = File::create(output_path)?;
+        Ok(match format {
+            Format::Parquet => AnySink::Parquet(ParquetSink::new(file, first_batch)?),
+            Format::Csv => AnySink::Csv(Box::new(CsvSink::new(file))),
+            Format::NdJson => AnySink::NdJson(NdJsonSink::new(file)),
+        })
+    }
+}
+
+impl RecordSink for AnySink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        match self {
+            AnySink::Parquet(sink) => sink.write_batch(batch),
+            AnySink::Csv(sink) => sink.write_batch(batch),
+            AnySink::NdJson(sink) => sink.write_batch(batch),
+        }
+    }
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            AnySink::Parquet(sink) => sink.finish(),
+            AnySink::Csv(sink) => sink.finish(),
+            AnySink::NdJson(sink) => sink.finish(),
+        }
+    }
+}